@@ -1,16 +1,29 @@
 mod quest;
 
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{fs::File, path::PathBuf};
 
 use clap::{Args, Parser, Subcommand};
+use colored::Colorize;
 
-use quest::QuestFile;
+use quest::{ConfiguredKeyValue, Method, Quest, QuestFile};
 
 fn main() {
-    env_logger::init();
-    QuestCli::parse().run();
+    let cli = QuestCli::parse();
+    let filter = match cli.verbose as i16 - cli.quiet as i16 {
+        i if i < 0 => log::LevelFilter::Off,
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(filter)
+        .parse_default_env()
+        .init();
+    cli.run();
 }
 
 fn print_version() -> &'static str {
@@ -37,6 +50,21 @@ struct QuestCli {
         help = "Load environment variables from file"
     )]
     env: PathBuf,
+    #[arg(
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        help = "Increase output verbosity. Repeat for more (--verbose --verbose)"
+    )]
+    verbose: u8,
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        help = "Decrease output verbosity. Each use cancels one --verbose; below zero, logging is off"
+    )]
+    quiet: u8,
     #[command(subcommand)]
     command: Commands,
 }
@@ -44,12 +72,14 @@ struct QuestCli {
 impl QuestCli {
     pub fn run(self) {
         log::debug!("{:?}", self);
+        let verbosity = self.verbose as i16 - self.quiet as i16;
         if dotenvy::from_path(&self.env).is_ok() {
             log::debug!("Environment loaded from {:?}", self.env);
         }
         let f = File::open(&self.file).expect("Could not open quest file.");
 
-        let questfile: QuestFile = serde_yaml::from_reader(f).expect("Could not parse quest file.");
+        let mut questfile: QuestFile =
+            serde_yaml::from_reader(f).expect("Could not parse quest file.");
 
         match self.command {
             Commands::Go(SendArgs {
@@ -61,14 +91,34 @@ impl QuestCli {
                 gzip,
                 deflate,
                 brotli,
+                dry_run,
+                expect,
             }) => {
                 let quest = questfile
                     .retrieve(&name)
                     .expect("Could not find quest with matching name.");
+                let vars = questfile.vars(quest, var.clone());
                 let url = questfile
                     .url(quest, var, param)
                     .expect("Could not construct url");
-                let headers = questfile.headers(quest, header);
+                let headers = questfile
+                    .headers(quest, header, &vars)
+                    .expect("Could not construct headers");
+
+                if dry_run {
+                    println!("{} {}", quest.method.pretty_string(), url);
+                    for (name, value) in &headers {
+                        println!("{}: {}", name, value.to_str().unwrap_or("<binary>"));
+                    }
+                    if let Some(json) = &quest.json {
+                        println!("{json}");
+                    }
+                    if let Some(body) = &quest.body {
+                        println!("{body}");
+                    }
+                    return;
+                }
+
                 let client = reqwest::blocking::ClientBuilder::new()
                     .gzip(gzip)
                     .deflate(deflate)
@@ -76,6 +126,13 @@ impl QuestCli {
                     .build()
                     .unwrap();
 
+                if verbosity >= 2 {
+                    eprintln!("{} {}", quest.method.pretty_string(), url);
+                    for (name, value) in &headers {
+                        eprintln!("{}: {}", name, value.to_str().unwrap_or("<binary>"));
+                    }
+                }
+
                 let mut req = client
                     .request(quest.method.into(), url)
                     .headers(headers)
@@ -91,9 +148,182 @@ impl QuestCli {
                     req = req.body(body.to_owned());
                 }
 
+                let start = Instant::now();
                 let resp = req.send().unwrap();
+                let elapsed = start.elapsed();
+
+                let status = resp.status();
+
+                if verbosity >= 1 {
+                    eprintln!("{:?} {}", resp.version(), status);
+                    for (name, value) in resp.headers() {
+                        eprintln!("{}: {}", name, value.to_str().unwrap_or("<binary>"));
+                    }
+                }
+                if verbosity >= 2 {
+                    eprintln!("elapsed: {elapsed:.3?}");
+                }
+                let expect = if expect.is_empty() {
+                    quest.expect.clone()
+                } else {
+                    expect
+                };
+                let passed = quest::status_matches(&expect, status);
+                if !expect.is_empty() {
+                    let label = if passed {
+                        "PASS".green()
+                    } else {
+                        "FAIL".red()
+                    };
+                    println!("{label} {status}");
+                }
 
                 println!("{}", resp.text().unwrap());
+
+                if !passed {
+                    std::process::exit(1);
+                }
+            }
+
+            Commands::Save(SaveArgs {
+                name,
+                method,
+                url,
+                var,
+                header,
+                param,
+                json,
+                body,
+            }) => {
+                let quest = Quest {
+                    name,
+                    method,
+                    url,
+                    vars: var.into_iter().map(into_kv).collect(),
+                    headers: header.into_iter().map(into_kv).collect(),
+                    params: param.into_iter().map(into_kv).collect(),
+                    json,
+                    body,
+                    expect: Vec::new(),
+                    extract: Vec::new(),
+                    needs: Vec::new(),
+                };
+                questfile.push(quest);
+                questfile
+                    .write(&self.file)
+                    .expect("Could not write quest file.");
+            }
+
+            Commands::Run(RunArgs {
+                names,
+                var,
+                header,
+                param,
+                timeout,
+                gzip,
+                deflate,
+                brotli,
+            }) => {
+                let client = reqwest::blocking::ClientBuilder::new()
+                    .gzip(gzip)
+                    .deflate(deflate)
+                    .brotli(brotli)
+                    .build()
+                    .unwrap();
+
+                let mut order = Vec::new();
+                let mut seen = HashSet::new();
+                for name in &names {
+                    resolve_order(&questfile, name, &mut order, &mut seen)
+                        .expect("Could not resolve quest dependencies.");
+                }
+
+                // Values scraped from earlier responses, fed into later quests as vars.
+                let mut captured: HashMap<String, String> = HashMap::new();
+
+                for name in &order {
+                    let quest = questfile
+                        .retrieve(name)
+                        .expect("Could not find quest with matching name.");
+
+                    let mut vars: Vec<(String, String)> = captured
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect();
+                    vars.extend(var.clone());
+
+                    let varmap = questfile.vars(quest, vars.clone());
+                    let url = questfile
+                        .url(quest, vars, param.clone())
+                        .expect("Could not construct url");
+                    let headers = questfile
+                        .headers(quest, header.clone(), &varmap)
+                        .expect("Could not construct headers");
+
+                    let mut req = client
+                        .request(quest.method.into(), url)
+                        .headers(headers)
+                        .timeout(Duration::from_secs(timeout));
+
+                    if let Some(json) = &quest.json {
+                        let json = QuestFile::substitute(json, &varmap)
+                            .expect("Could not substitute variables in json body");
+                        req = req
+                            .body(json)
+                            .header("Content-Type", "application/json");
+                    }
+                    if let Some(body) = &quest.body {
+                        let body = QuestFile::substitute(body, &varmap)
+                            .expect("Could not substitute variables in body");
+                        req = req.body(body);
+                    }
+
+                    let resp = req.send().unwrap();
+                    let status = resp.status();
+                    let passed = quest::status_matches(&quest.expect, status);
+                    if !quest.expect.is_empty() {
+                        let label = if passed {
+                            "PASS".green()
+                        } else {
+                            "FAIL".red()
+                        };
+                        println!("{label} {name} {status}");
+                    }
+
+                    let text = resp.text().unwrap();
+                    if !passed {
+                        eprintln!("{text}");
+                        std::process::exit(1);
+                    }
+
+                    if quest.extract.is_empty() {
+                        println!("{text}");
+                    } else {
+                        let value: serde_json::Value = match serde_json::from_str(&text) {
+                            Ok(value) => value,
+                            Err(err) => {
+                                eprintln!(
+                                    "Could not parse response of `{name}` as JSON for extraction: {err}"
+                                );
+                                std::process::exit(1);
+                            }
+                        };
+                        for extract in &quest.extract {
+                            match select(&value, &extract.jsonpath) {
+                                Some(found) => {
+                                    captured.insert(extract.name.clone(), json_to_string(found));
+                                }
+                                None => {
+                                    eprintln!(
+                                        "Could not extract `{}` from response of `{name}`.",
+                                        extract.jsonpath
+                                    );
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                    }
+                }
             }
 
             Commands::Ls => {
@@ -107,6 +337,8 @@ impl QuestCli {
 #[derive(Clone, Debug, Subcommand)]
 enum Commands {
     Go(SendArgs),
+    Save(SaveArgs),
+    Run(RunArgs),
     Ls,
 }
 
@@ -128,6 +360,101 @@ struct SendArgs {
     deflate: bool,
     #[arg(long, default_value = "false", help = "Use brotli compression")]
     brotli: bool,
+    #[arg(
+        long,
+        default_value = "false",
+        help = "Print the fully-resolved request instead of sending it"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        help = "Acceptable response status code(s) or class, e.g. 200 or 2xx. Overrides the quest. Can be used multiple times"
+    )]
+    expect: Vec<String>,
+}
+
+#[derive(Clone, Debug, Args)]
+struct SaveArgs {
+    #[arg()]
+    name: String,
+    #[arg(short, long, help = "HTTP method for the quest")]
+    method: Method,
+    #[arg()]
+    url: String,
+    #[arg(short, long, value_parser = parse_key_val::<String, String>, help = "Overrides or adds value. Can be used multiple times")]
+    var: Vec<(String, String)>,
+    #[arg(short = 'H', long, value_parser = parse_key_val::<String, String>, help = "Overrides or adds value. Can be used multiple times")]
+    header: Vec<(String, String)>,
+    #[arg(short, long, value_parser = parse_key_val::<String, String>, help = "Overrides or adds value. Can be used multiple times")]
+    param: Vec<(String, String)>,
+    #[arg(long, help = "JSON body to send with the quest")]
+    json: Option<String>,
+    #[arg(long, help = "Raw body to send with the quest")]
+    body: Option<String>,
+}
+
+#[derive(Clone, Debug, Args)]
+struct RunArgs {
+    #[arg(required = true, help = "Quests to run, in order")]
+    names: Vec<String>,
+    #[arg(short, long, value_parser = parse_key_val::<String, String>, help = "Overrides or adds value. Can be used multiple times")]
+    var: Vec<(String, String)>,
+    #[arg(short = 'H', long, value_parser = parse_key_val::<String, String>, help = "Overrides or adds value. Can be used multiple times")]
+    header: Vec<(String, String)>,
+    #[arg(short, long, value_parser = parse_key_val::<String, String>, help = "Overrides or adds value. Can be used multiple times")]
+    param: Vec<(String, String)>,
+    #[arg(short, long, default_value = "30", help = "Timeout seconds")]
+    timeout: u64,
+    #[arg(long, default_value = "false", help = "Use gzip compression")]
+    gzip: bool,
+    #[arg(long, default_value = "false", help = "Use deflate compression")]
+    deflate: bool,
+    #[arg(long, default_value = "false", help = "Use brotli compression")]
+    brotli: bool,
+}
+
+fn into_kv((name, value): (String, String)) -> ConfiguredKeyValue {
+    ConfiguredKeyValue::Value { name, value }
+}
+
+/// Expands `name` and its `needs` into a de-duplicated execution order where a
+/// quest's dependencies always precede it. Already-seen names short-circuit,
+/// which also guards against dependency cycles.
+fn resolve_order(
+    questfile: &QuestFile,
+    name: &str,
+    order: &mut Vec<String>,
+    seen: &mut HashSet<String>,
+) -> Result<(), quest::QuestError> {
+    if !seen.insert(name.to_string()) {
+        return Ok(());
+    }
+    let quest = questfile.retrieve(name)?;
+    for need in &quest.needs {
+        resolve_order(questfile, need, order, seen)?;
+    }
+    order.push(name.to_string());
+    Ok(())
+}
+
+/// Walks a dotted path (e.g. `data.token` or `items.0.id`) into a JSON value.
+fn select<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let mut current = value;
+    for segment in path.split('.') {
+        current = match current {
+            serde_json::Value::Object(map) => map.get(segment)?,
+            serde_json::Value::Array(arr) => arr.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn json_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
 }
 
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
@@ -1,8 +1,9 @@
-use std::{collections::HashMap, env::VarError};
+use std::{collections::HashMap, env::VarError, fs::File, path::Path};
 
 use colored::{ColoredString, Colorize};
 use itertools::*;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use url::Url;
@@ -22,6 +23,10 @@ pub enum QuestError {
     InvalidUrl(#[from] url::ParseError),
     #[error("A global or quest specific url must be configured.")]
     MissingUrl,
+    #[error("Could not read or write the quest file.")]
+    Io(#[from] std::io::Error),
+    #[error("Could not serialize the quest file.")]
+    Serialize(#[from] serde_yaml::Error),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -56,8 +61,11 @@ impl ConfiguredKeyValue {
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 #[serde(default)]
 pub struct QuestFile {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     headers: Vec<ConfiguredKeyValue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     vars: Vec<ConfiguredKeyValue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     params: Vec<ConfiguredKeyValue>,
     quests: Vec<Quest>,
 }
@@ -100,20 +108,43 @@ impl QuestFile {
             .collect()
     }
 
-    pub fn headers(&self, quest: &Quest, headers: Vec<(String, String)>) -> HeaderMap {
+    pub fn headers(
+        &self,
+        quest: &Quest,
+        headers: Vec<(String, String)>,
+        vars: &HashMap<String, String>,
+    ) -> Result<HeaderMap, QuestError> {
         self.headers
             .iter()
             .chain(quest.headers.iter())
             .map(|var| (var.name(), var.value().unwrap()))
             .chain(headers)
             .map(|(name, var)| {
-                (
+                let var = envsubst::substitute(var, vars)?;
+                Ok((
                     HeaderName::from_lowercase(name.to_lowercase().as_bytes()).unwrap(),
                     HeaderValue::from_str(&var).unwrap(),
-                )
+                ))
             })
             .collect()
     }
+    /// Substitutes `${var}` references in `template` using the supplied map,
+    /// mirroring the interpolation [`QuestFile::url`] performs on the URL so
+    /// that header values and request bodies resolve the same variables.
+    pub fn substitute(
+        template: &str,
+        vars: &HashMap<String, String>,
+    ) -> Result<String, QuestError> {
+        Ok(envsubst::substitute(template, vars)?)
+    }
+    pub fn push(&mut self, quest: Quest) {
+        self.quests.push(quest);
+    }
+    pub fn write(&self, path: &Path) -> Result<(), QuestError> {
+        let f = File::create(path)?;
+        serde_yaml::to_writer(f, self)?;
+        Ok(())
+    }
     #[allow(unstable_name_collisions)]
     pub fn pretty_print(&self) {
         let fmt_len = self.quests.iter().fold(1, |acc, q| acc.max(q.name.len())) + 4;
@@ -143,17 +174,52 @@ pub struct Quest {
     pub name: String,
     pub method: Method,
     pub url: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub vars: Vec<ConfiguredKeyValue>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub headers: Vec<ConfiguredKeyValue>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub params: Vec<ConfiguredKeyValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub json: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expect: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extract: Vec<Extract>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub needs: Vec<String>,
+}
+
+/// Captures a value from a quest's JSON response and binds it to a variable
+/// name so later quests in a chain can reference it as `${name}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Extract {
+    pub name: String,
+    pub jsonpath: String,
+}
+
+/// Returns `true` when `status` satisfies any of the `patterns`. A pattern is
+/// either an exact code (`200`) or a class with `x` wildcards (`2xx`). An empty
+/// pattern list places no constraint and always matches.
+pub fn status_matches(patterns: &[String], status: StatusCode) -> bool {
+    if patterns.is_empty() {
+        return true;
+    }
+    let code = status.as_u16().to_string();
+    patterns.iter().any(|pattern| {
+        pattern.len() == code.len()
+            && pattern
+                .chars()
+                .zip(code.chars())
+                .all(|(p, c)| p == 'x' || p == 'X' || p == c)
+    })
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, clap::ValueEnum,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum Method {
     Get,